@@ -1,4 +1,8 @@
+use std::time::Duration;
+
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 
 use crate::shared::{
     PgConnectionConfig, PgConnectionConfigWithoutSecrets, ValidationError, batch::BatchConfig,
@@ -16,6 +20,74 @@ fn default_slot_prefix() -> String {
     DEFAULT_SLOT_PREFIX.to_string()
 }
 
+/// The `wal_level` setting of the upstream Postgres instance, as reported by `SHOW wal_level`.
+///
+/// Logical replication requires `Logical`; `Minimal` and `Replica` cannot produce the row-level
+/// change stream the pipeline needs, and slot creation will fail deep in the replication
+/// protocol if we let a pipeline start against one of them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WalLevel {
+    Minimal,
+    Replica,
+    Logical,
+}
+
+impl TryFrom<&str> for WalLevel {
+    type Error = ValidationError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "minimal" => Ok(WalLevel::Minimal),
+            "replica" => Ok(WalLevel::Replica),
+            "logical" => Ok(WalLevel::Logical),
+            other => Err(ValidationError::UnknownWalLevel(other.to_string())),
+        }
+    }
+}
+
+/// Whether a table-sync/apply failure is worth retrying.
+///
+/// Transient failures (a dropped connection) should be retried indefinitely with backoff, since
+/// they say nothing about whether the operation itself is valid. Permanent failures (a missing
+/// column, a permission error) will never succeed on retry, so they should fail fast instead of
+/// burning through `table_error_retry_max_attempts` with the same doomed attempt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RetryClassification {
+    Transient,
+    Permanent,
+}
+
+impl RetryClassification {
+    /// Classifies a connection-level I/O error by its [`std::io::ErrorKind`].
+    pub fn for_io_error_kind(kind: std::io::ErrorKind) -> Self {
+        use std::io::ErrorKind::{ConnectionAborted, ConnectionRefused, ConnectionReset};
+
+        match kind {
+            ConnectionRefused | ConnectionReset | ConnectionAborted => Self::Transient,
+            _ => Self::Permanent,
+        }
+    }
+
+    /// Classifies an observed table-sync/apply failure, given the [`std::io::ErrorKind`] of its
+    /// underlying I/O error, if any. Errors that didn't come from the connection itself (a
+    /// missing column, a permission error, ...) are always [`Self::Permanent`].
+    pub fn for_table_error(io_error_kind: Option<std::io::ErrorKind>) -> Self {
+        match io_error_kind {
+            Some(kind) => Self::for_io_error_kind(kind),
+            None => Self::Permanent,
+        }
+    }
+}
+
+/// What a worker should do after a table-sync/apply attempt fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetryDecision {
+    /// Wait this long, then try again.
+    Retry(Duration),
+    /// Stop retrying and surface the error.
+    GiveUp,
+}
+
 /// Configuration for an ETL pipeline.
 ///
 /// Contains all settings required to run a replication pipeline including
@@ -37,9 +109,17 @@ pub struct PipelineConfig {
     pub pg_connection: PgConnectionConfig,
     /// Batch processing configuration.
     pub batch: BatchConfig,
-    /// Number of milliseconds between one retry and another when a table error occurs.
-    pub table_error_retry_delay_ms: u64,
+    /// Starting delay, in milliseconds, for the exponential backoff applied between table-error
+    /// retries.
+    pub table_error_retry_base_delay_ms: u64,
+    /// Upper bound, in milliseconds, on the backoff delay between table-error retries.
+    pub table_error_retry_max_delay_ms: u64,
+    /// Multiplier applied to the previous delay on each retry attempt, before jitter.
+    pub table_error_retry_multiplier: f64,
     /// Maximum number of automatic retry attempts before requiring manual intervention.
+    ///
+    /// This only bounds permanent-failure retries; transient connection errors (see
+    /// [`RetryClassification`]) are retried indefinitely with backoff.
     pub table_error_retry_max_attempts: u32,
     /// Maximum number of table sync workers that can run at a time
     pub max_table_sync_workers: u16,
@@ -48,6 +128,9 @@ pub struct PipelineConfig {
     /// Table sync slots will be named: `{slot_prefix}_table_sync_{pipeline_id}_{table_id}`
     #[serde(default = "default_slot_prefix")]
     pub slot_prefix: String,
+    /// Maximum bytes of WAL an inactive replication slot belonging to this pipeline may retain
+    /// before [`Self::check_slot_wal_retention`] flags it.
+    pub max_slot_wal_bytes: u64,
 }
 
 impl PipelineConfig {
@@ -65,6 +148,19 @@ impl PipelineConfig {
             return Err(ValidationError::TableErrorRetryMaxAttemptsZero);
         }
 
+        if self.table_error_retry_base_delay_ms > self.table_error_retry_max_delay_ms {
+            return Err(ValidationError::RetryBaseDelayExceedsMaxDelay {
+                base_delay_ms: self.table_error_retry_base_delay_ms,
+                max_delay_ms: self.table_error_retry_max_delay_ms,
+            });
+        }
+
+        if self.table_error_retry_multiplier < 1.0 {
+            return Err(ValidationError::RetryMultiplierTooSmall {
+                multiplier: self.table_error_retry_multiplier,
+            });
+        }
+
         if self.slot_prefix.is_empty() {
             return Err(ValidationError::SlotPrefixEmpty);
         }
@@ -78,6 +174,200 @@ impl PipelineConfig {
 
         Ok(())
     }
+
+    /// Validates that the upstream Postgres instance is actually set up for logical replication.
+    ///
+    /// Unlike [`Self::validate`], this requires a live connection to the source database, since
+    /// it checks `wal_level` and the remaining replication slot/sender headroom on the server.
+    /// Run this once up front so a misconfigured upstream produces a single clear error instead
+    /// of an opaque failure deep inside slot creation.
+    pub async fn validate_source(&self, pool: &PgPool) -> Result<(), ValidationError> {
+        let wal_level: String = sqlx::query_scalar("show wal_level")
+            .fetch_one(pool)
+            .await
+            .map_err(ValidationError::Database)?;
+        let wal_level = WalLevel::try_from(wal_level.as_str())?;
+        if wal_level != WalLevel::Logical {
+            return Err(ValidationError::WalLevelNotLogical(wal_level));
+        }
+
+        let max_replication_slots =
+            Self::fetch_server_setting_i64(pool, "max_replication_slots").await?;
+        let max_wal_senders = Self::fetch_server_setting_i64(pool, "max_wal_senders").await?;
+
+        // On restart, this pipeline's own apply/table-sync slots (and any sender already
+        // consuming them) are still around from the previous run, but `required_slots` asks for
+        // headroom for those same slots. Exclude them by name, matching the naming convention
+        // from `EtlReplicationSlot` in `etl-postgres`, so a pipeline doesn't fail its own
+        // preflight check purely because it already exists.
+        let own_apply_slot_name = format!("{}_apply_{}", self.slot_prefix, self.id);
+        // `_` is a single-character wildcard in LIKE, so it is escaped to match only this
+        // pipeline's table-sync slots (e.g. pipeline 1 must not also exclude pipeline 12's).
+        let own_table_sync_slot_prefix =
+            format!("{}_table_sync_{}_", self.slot_prefix, self.id).replace('_', "\\_") + "%";
+
+        let used_slots: i64 = sqlx::query_scalar(
+            "select count(*) from pg_replication_slots
+             where slot_name <> $1 and slot_name not like $2",
+        )
+        .bind(&own_apply_slot_name)
+        .bind(&own_table_sync_slot_prefix)
+        .fetch_one(pool)
+        .await
+        .map_err(ValidationError::Database)?;
+        // Walsenders are consumed by anything streaming from the primary (including physical
+        // replicas and base backups, which hold no slot), while inactive slots hold no
+        // walsender at all, so this must be counted separately from `used_slots`.
+        let used_senders: i64 = sqlx::query_scalar(
+            "select count(*) from pg_stat_replication
+             where slot_name is null or (slot_name <> $1 and slot_name not like $2)",
+        )
+        .bind(&own_apply_slot_name)
+        .bind(&own_table_sync_slot_prefix)
+        .fetch_one(pool)
+        .await
+        .map_err(ValidationError::Database)?;
+
+        let required_slots = 1 + i64::from(self.max_table_sync_workers);
+        let available_slots = max_replication_slots - used_slots;
+        let available_senders = max_wal_senders - used_senders;
+        let available = available_slots.min(available_senders).max(0);
+
+        if required_slots > available {
+            return Err(ValidationError::InsufficientReplicationHeadroom {
+                required: required_slots,
+                available,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Reads a numeric `SHOW <setting>` value, which Postgres always returns as text.
+    async fn fetch_server_setting_i64(pool: &PgPool, setting: &str) -> Result<i64, ValidationError> {
+        let raw: String = sqlx::query_scalar(&format!("show {setting}"))
+            .fetch_one(pool)
+            .await
+            .map_err(ValidationError::Database)?;
+
+        raw.parse()
+            .map_err(|_| ValidationError::InvalidServerSetting {
+                setting: setting.to_string(),
+                value: raw,
+            })
+    }
+
+    /// Computes the backoff delay to wait before the `attempt`-th retry (0-indexed), with full
+    /// jitter applied so that many workers retrying at once don't reconnect in lockstep.
+    ///
+    /// `delay = min(max_delay, base * multiplier^attempt)`, then a uniform random delay in
+    /// `[0, delay]` is chosen.
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        Self::backoff_delay(
+            self.table_error_retry_base_delay_ms,
+            self.table_error_retry_max_delay_ms,
+            self.table_error_retry_multiplier,
+            attempt,
+        )
+    }
+
+    /// Pure backoff math behind [`Self::retry_delay`], split out so the capping/growth behavior
+    /// can be unit tested without constructing a full [`PipelineConfig`].
+    fn backoff_delay(base_delay_ms: u64, max_delay_ms: u64, multiplier: f64, attempt: u32) -> Duration {
+        let uncapped_delay_ms = base_delay_ms as f64 * multiplier.powi(attempt as i32);
+        let delay_ms = uncapped_delay_ms.min(max_delay_ms as f64) as u64;
+
+        let jittered_delay_ms = rand::rng().random_range(0..=delay_ms);
+        Duration::from_millis(jittered_delay_ms)
+    }
+
+    /// Decides what to do after the `attempt`-th table-sync/apply failure (0-indexed).
+    ///
+    /// Connection-level errors (see [`RetryClassification`]) are retried indefinitely with
+    /// backoff, since a blip in connectivity says nothing about whether the operation itself is
+    /// valid, and `attempt` for them should be tracked separately so they never count against
+    /// `table_error_retry_max_attempts`. Every other error is permanent: it is retried up to
+    /// `table_error_retry_max_attempts` times and then gives up, so a genuinely broken schema or
+    /// permission error fails fast instead of looping forever.
+    pub fn next_retry(&self, io_error_kind: Option<std::io::ErrorKind>, attempt: u32) -> RetryDecision {
+        let classification = RetryClassification::for_table_error(io_error_kind);
+        Self::decide_retry(
+            classification,
+            attempt,
+            self.table_error_retry_max_attempts,
+            self.retry_delay(attempt),
+        )
+    }
+
+    /// Pure decision logic behind [`Self::next_retry`], split out so the attempt-boundary check
+    /// can be unit tested without constructing a full [`PipelineConfig`].
+    fn decide_retry(
+        classification: RetryClassification,
+        attempt: u32,
+        max_attempts: u32,
+        delay: Duration,
+    ) -> RetryDecision {
+        match classification {
+            RetryClassification::Transient => RetryDecision::Retry(delay),
+            RetryClassification::Permanent => {
+                if attempt >= max_attempts {
+                    RetryDecision::GiveUp
+                } else {
+                    RetryDecision::Retry(delay)
+                }
+            }
+        }
+    }
+
+    /// Checks a slot's WAL-retention lag against [`Self::max_slot_wal_bytes`].
+    ///
+    /// Only inactive slots are flagged: an active slot is still being consumed, so its retained
+    /// WAL is expected to shrink as soon as the consumer catches up. `retained_wal_bytes` is
+    /// `None` when the slot has never confirmed a flush (e.g. `SlotLag::retained_wal_bytes` from
+    /// `etl-postgres`), which is treated as exceeding the threshold rather than as zero lag,
+    /// since the slot's true retention is unknown and could date back to its creation.
+    pub fn check_slot_wal_retention(
+        &self,
+        slot_name: &str,
+        active: bool,
+        retained_wal_bytes: Option<i64>,
+    ) -> Result<(), ValidationError> {
+        Self::check_wal_retention_threshold(
+            slot_name,
+            active,
+            retained_wal_bytes,
+            self.max_slot_wal_bytes,
+        )
+    }
+
+    /// Pure threshold check behind [`Self::check_slot_wal_retention`], split out so the
+    /// active/inactive short-circuit and the `None`-means-exceeds branch can be unit tested
+    /// without constructing a full [`PipelineConfig`].
+    fn check_wal_retention_threshold(
+        slot_name: &str,
+        active: bool,
+        retained_wal_bytes: Option<i64>,
+        max_slot_wal_bytes: u64,
+    ) -> Result<(), ValidationError> {
+        if active {
+            return Ok(());
+        }
+
+        let exceeds_threshold = match retained_wal_bytes {
+            Some(bytes) => bytes.max(0) as u64 > max_slot_wal_bytes,
+            None => true,
+        };
+
+        if exceeds_threshold {
+            return Err(ValidationError::SlotWalRetentionExceeded {
+                slot_name: slot_name.to_string(),
+                retained_wal_bytes,
+                max_slot_wal_bytes,
+            });
+        }
+
+        Ok(())
+    }
 }
 
 /// Same as [`PipelineConfig`] but without secrets. This type
@@ -97,8 +387,13 @@ pub struct PipelineConfigWithoutSecrets {
     pub pg_connection: PgConnectionConfigWithoutSecrets,
     /// Batch processing configuration.
     pub batch: BatchConfig,
-    /// Number of milliseconds between one retry and another when a table error occurs.
-    pub table_error_retry_delay_ms: u64,
+    /// Starting delay, in milliseconds, for the exponential backoff applied between table-error
+    /// retries.
+    pub table_error_retry_base_delay_ms: u64,
+    /// Upper bound, in milliseconds, on the backoff delay between table-error retries.
+    pub table_error_retry_max_delay_ms: u64,
+    /// Multiplier applied to the previous delay on each retry attempt, before jitter.
+    pub table_error_retry_multiplier: f64,
     /// Maximum number of automatic retry attempts before requiring manual intervention.
     pub table_error_retry_max_attempts: u32,
     /// Maximum number of table sync workers that can run at a time
@@ -106,6 +401,8 @@ pub struct PipelineConfigWithoutSecrets {
     /// Custom prefix for replication slot names.
     #[serde(default = "default_slot_prefix")]
     pub slot_prefix: String,
+    /// Maximum bytes of WAL an inactive replication slot belonging to this pipeline may retain.
+    pub max_slot_wal_bytes: u64,
 }
 
 impl From<PipelineConfig> for PipelineConfigWithoutSecrets {
@@ -115,10 +412,139 @@ impl From<PipelineConfig> for PipelineConfigWithoutSecrets {
             publication_name: value.publication_name,
             pg_connection: value.pg_connection.into(),
             batch: value.batch,
-            table_error_retry_delay_ms: value.table_error_retry_delay_ms,
+            table_error_retry_base_delay_ms: value.table_error_retry_base_delay_ms,
+            table_error_retry_max_delay_ms: value.table_error_retry_max_delay_ms,
+            table_error_retry_multiplier: value.table_error_retry_multiplier,
             table_error_retry_max_attempts: value.table_error_retry_max_attempts,
             max_table_sync_workers: value.max_table_sync_workers,
             slot_prefix: value.slot_prefix,
+            max_slot_wal_bytes: value.max_slot_wal_bytes,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay_caps_at_max_delay() {
+        let delay = PipelineConfig::backoff_delay(1_000, 5_000, 10.0, 3);
+        assert!(delay <= Duration::from_millis(5_000));
+    }
+
+    #[test]
+    fn test_backoff_delay_respects_multiplier_growth_before_cap() {
+        for attempt in 0..4 {
+            let delay = PipelineConfig::backoff_delay(100, u64::MAX, 2.0, attempt);
+            let upper_bound = 100.0 * 2f64.powi(attempt as i32);
+            assert!(delay <= Duration::from_millis(upper_bound as u64));
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay_is_zero_with_zero_base_delay() {
+        let delay = PipelineConfig::backoff_delay(0, 5_000, 2.0, 2);
+        assert_eq!(delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_retry_classification_dispatches_connection_errors_as_transient() {
+        assert_eq!(
+            RetryClassification::for_io_error_kind(std::io::ErrorKind::ConnectionReset),
+            RetryClassification::Transient
+        );
+        assert_eq!(
+            RetryClassification::for_io_error_kind(std::io::ErrorKind::ConnectionRefused),
+            RetryClassification::Transient
+        );
+        assert_eq!(
+            RetryClassification::for_io_error_kind(std::io::ErrorKind::ConnectionAborted),
+            RetryClassification::Transient
+        );
+    }
+
+    #[test]
+    fn test_retry_classification_dispatches_other_errors_as_permanent() {
+        assert_eq!(
+            RetryClassification::for_io_error_kind(std::io::ErrorKind::NotFound),
+            RetryClassification::Permanent
+        );
+        assert_eq!(
+            RetryClassification::for_table_error(None),
+            RetryClassification::Permanent
+        );
+    }
+
+    #[test]
+    fn test_decide_retry_retries_transient_errors_past_max_attempts() {
+        let decision = PipelineConfig::decide_retry(
+            RetryClassification::Transient,
+            /* attempt */ 100,
+            /* max_attempts */ 3,
+            Duration::from_millis(10),
+        );
+
+        assert_eq!(decision, RetryDecision::Retry(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_decide_retry_permanent_error_retries_below_max_attempts() {
+        let decision = PipelineConfig::decide_retry(
+            RetryClassification::Permanent,
+            /* attempt */ 2,
+            /* max_attempts */ 3,
+            Duration::from_millis(10),
+        );
+
+        assert_eq!(decision, RetryDecision::Retry(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_decide_retry_permanent_error_gives_up_at_max_attempts() {
+        let decision = PipelineConfig::decide_retry(
+            RetryClassification::Permanent,
+            /* attempt */ 3,
+            /* max_attempts */ 3,
+            Duration::from_millis(10),
+        );
+
+        assert_eq!(decision, RetryDecision::GiveUp);
+    }
+
+    #[test]
+    fn test_wal_retention_threshold_ignores_active_slots_over_threshold() {
+        let result =
+            PipelineConfig::check_wal_retention_threshold("slot", true, Some(1_000), 100);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_wal_retention_threshold_passes_inactive_slot_under_threshold() {
+        let result =
+            PipelineConfig::check_wal_retention_threshold("slot", false, Some(50), 100);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_wal_retention_threshold_flags_inactive_slot_over_threshold() {
+        let result =
+            PipelineConfig::check_wal_retention_threshold("slot", false, Some(1_000), 100);
+        assert!(matches!(
+            result,
+            Err(ValidationError::SlotWalRetentionExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wal_retention_threshold_treats_null_confirmed_flush_lsn_as_exceeding() {
+        // No confirmed flush (e.g. a slot that has never confirmed a flush) means unknown,
+        // unbounded retention, not zero lag, even though the threshold is generous.
+        let result =
+            PipelineConfig::check_wal_retention_threshold("slot", false, None, u64::MAX);
+        assert!(matches!(
+            result,
+            Err(ValidationError::SlotWalRetentionExceeded { .. })
+        ));
+    }
+}
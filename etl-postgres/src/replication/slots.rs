@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 
 use sqlx::PgPool;
+use sqlx::postgres::types::PgLsn;
 use thiserror::Error;
 use tokio_postgres::types::Oid;
 
@@ -27,19 +28,42 @@ pub enum EtlReplicationSlotError {
     InvalidSlotName(String),
 }
 
-/// Parsed representation of a replication slot name.
+/// Parsed representation of a replication slot name, plus the in-memory resume position (if
+/// any) that the owning worker should start decoding from.
+///
+/// The resume position is never part of the slot *name* — it is carried alongside it so that
+/// the same value travels from [`Self::resume_point`] through to the start-up logic without a
+/// separate parameter.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum EtlReplicationSlot {
     /// Apply worker slot for a pipeline.
     Apply {
         pipeline_id: u64,
         prefix: Cow<'static, str>,
+        /// The last LSN Postgres has confirmed as flushed for this slot, if resuming.
+        confirmed_flush_lsn: Option<PgLsn>,
+        /// Number of decoded messages observed at `confirmed_flush_lsn` so far, to persist after
+        /// the next committed batch.
+        resume_offset: u64,
+        /// Number of messages still to skip at `confirmed_flush_lsn` because they were already
+        /// applied before the last restart. Distinct from `resume_offset`: this only ever counts
+        /// down to zero, while `resume_offset` keeps counting every message seen at this LSN.
+        skip_remaining: u64,
     },
     /// Table sync worker slot for a pipeline and table.
     TableSync {
         pipeline_id: u64,
         table_id: TableId,
         prefix: Cow<'static, str>,
+        /// The last LSN Postgres has confirmed as flushed for this slot, if resuming.
+        confirmed_flush_lsn: Option<PgLsn>,
+        /// Number of decoded messages observed at `confirmed_flush_lsn` so far, to persist after
+        /// the next committed batch.
+        resume_offset: u64,
+        /// Number of messages still to skip at `confirmed_flush_lsn` because they were already
+        /// applied before the last restart. Distinct from `resume_offset`: this only ever counts
+        /// down to zero, while `resume_offset` keeps counting every message seen at this LSN.
+        skip_remaining: u64,
     },
 }
 
@@ -49,6 +73,9 @@ impl EtlReplicationSlot {
         Self::Apply {
             pipeline_id,
             prefix: prefix.into(),
+            confirmed_flush_lsn: None,
+            resume_offset: 0,
+            skip_remaining: 0,
         }
     }
 
@@ -62,9 +89,104 @@ impl EtlReplicationSlot {
             pipeline_id,
             table_id,
             prefix: prefix.into(),
+            confirmed_flush_lsn: None,
+            resume_offset: 0,
+            skip_remaining: 0,
+        }
+    }
+
+    /// Attaches a resume position to this slot, so the owning worker starts decoding at
+    /// `lsn` and skips the first `offset` messages found at that exact LSN.
+    pub fn with_resume_point(mut self, lsn: PgLsn, offset: u64) -> Self {
+        let (confirmed_flush_lsn, resume_offset, skip_remaining) = self.resume_fields_mut();
+        *confirmed_flush_lsn = Some(lsn);
+        *resume_offset = offset;
+        *skip_remaining = offset;
+
+        self
+    }
+
+    /// Returns mutable access to the `(confirmed_flush_lsn, resume_offset, skip_remaining)`
+    /// triple carried by either variant.
+    fn resume_fields_mut(&mut self) -> (&mut Option<PgLsn>, &mut u64, &mut u64) {
+        match self {
+            Self::Apply {
+                confirmed_flush_lsn,
+                resume_offset,
+                skip_remaining,
+                ..
+            }
+            | Self::TableSync {
+                confirmed_flush_lsn,
+                resume_offset,
+                skip_remaining,
+                ..
+            } => (confirmed_flush_lsn, resume_offset, skip_remaining),
+        }
+    }
+
+    /// Consumes one decoded message at `message_lsn`, returning whether the owning worker should
+    /// skip it because it was already applied before the last restart.
+    ///
+    /// A single LSN can contain multiple row events, so a resume point is `(lsn, offset)`:
+    /// `skip_remaining` counts down once per message seen at exactly `confirmed_flush_lsn`,
+    /// independently of `resume_offset`, which keeps counting every message seen at that LSN so
+    /// [`Self::resume_point_to_persist`] always returns the right value to persist after the
+    /// batch containing this message commits. Once a message arrives at a later LSN, both
+    /// counters reset: the new LSN has never been observed before, so nothing is skipped there.
+    pub fn observe_message(&mut self, message_lsn: PgLsn) -> bool {
+        let (confirmed_flush_lsn, resume_offset, skip_remaining) = self.resume_fields_mut();
+
+        if *confirmed_flush_lsn != Some(message_lsn) {
+            *confirmed_flush_lsn = Some(message_lsn);
+            *resume_offset = 0;
+            *skip_remaining = 0;
+        }
+
+        if *skip_remaining > 0 {
+            *skip_remaining -= 1;
+            return true;
+        }
+
+        *resume_offset += 1;
+        false
+    }
+
+    /// Returns the `(lsn, offset)` resume point to persist after a committed batch, if this
+    /// slot has observed any message yet.
+    pub fn resume_point_to_persist(&self) -> Option<(PgLsn, u64)> {
+        match self {
+            Self::Apply {
+                confirmed_flush_lsn: Some(lsn),
+                resume_offset,
+                ..
+            }
+            | Self::TableSync {
+                confirmed_flush_lsn: Some(lsn),
+                resume_offset,
+                ..
+            } => Some((*lsn, *resume_offset)),
+            _ => None,
         }
     }
 
+    /// Reads the `confirmed_flush_lsn` of an already-existing replication slot.
+    ///
+    /// Returns `None` when the slot does not exist yet, or exists but has not confirmed a
+    /// flush. Either case means the caller should create the slot and start from its
+    /// consistent point rather than resuming.
+    pub async fn resume_point(pool: &PgPool, slot_name: &str) -> sqlx::Result<Option<PgLsn>> {
+        let confirmed_flush_lsn: Option<PgLsn> = sqlx::query_scalar(
+            "select confirmed_flush_lsn from pg_replication_slots where slot_name = $1",
+        )
+        .bind(slot_name)
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+
+        Ok(confirmed_flush_lsn)
+    }
+
     /// Returns the prefix of apply sync slot for a pipeline.
     pub fn apply_prefix(
         pipeline_id: u64,
@@ -92,6 +214,51 @@ impl EtlReplicationSlot {
 
         Ok(prefix)
     }
+
+    /// Reads the WAL-retention lag for an already-existing replication slot.
+    ///
+    /// Returns `None` if no slot with this name exists. `retained_wal_bytes` is `None` when
+    /// `confirmed_flush_lsn` is itself `NULL` — i.e. the slot has never confirmed a flush, which
+    /// is the worst case since it may be retaining WAL since its creation. Callers must treat
+    /// that as unbounded/unknown retention, not as zero lag.
+    pub async fn lag(pool: &PgPool, slot_name: &str) -> sqlx::Result<Option<SlotLag>> {
+        let row: Option<(bool, Option<PgLsn>, Option<i64>)> = sqlx::query_as(
+            r#"
+            select
+                active,
+                confirmed_flush_lsn,
+                pg_wal_lsn_diff(pg_current_wal_lsn(), confirmed_flush_lsn)::bigint
+            from pg_replication_slots
+            where slot_name = $1
+            "#,
+        )
+        .bind(slot_name)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(
+            row.map(|(active, confirmed_flush_lsn, retained_wal_bytes)| SlotLag {
+                slot_name: slot_name.to_string(),
+                active,
+                confirmed_flush_lsn,
+                retained_wal_bytes,
+            }),
+        )
+    }
+}
+
+/// WAL-retention lag for a single replication slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotLag {
+    pub slot_name: String,
+    pub active: bool,
+    pub confirmed_flush_lsn: Option<PgLsn>,
+    /// Bytes of WAL Postgres is retaining on behalf of this slot, i.e.
+    /// `pg_wal_lsn_diff(pg_current_wal_lsn(), confirmed_flush_lsn)`.
+    ///
+    /// `None` when `confirmed_flush_lsn` is `NULL` (the slot has never confirmed a flush), in
+    /// which case retention is unknown and should be treated as unbounded rather than zero.
+    pub retained_wal_bytes: Option<i64>,
 }
 
 impl TryFrom<&str> for EtlReplicationSlot {
@@ -156,6 +323,7 @@ impl TryFrom<EtlReplicationSlot> for String {
             EtlReplicationSlot::Apply {
                 pipeline_id,
                 prefix,
+                ..
             } => {
                 format!("{prefix}_{APPLY_SUFFIX}_{pipeline_id}")
             }
@@ -163,6 +331,7 @@ impl TryFrom<EtlReplicationSlot> for String {
                 pipeline_id,
                 table_id,
                 prefix,
+                ..
             } => {
                 format!(
                     "{prefix}_{TABLE_SYNC_SUFFIX}_{pipeline_id}_{}",
@@ -222,6 +391,183 @@ pub async fn delete_pipeline_replication_slots(
     Ok(())
 }
 
+/// Lists all replication slots owned by a pipeline, discovered directly from
+/// `pg_replication_slots` rather than requiring the caller to already know every [`TableId`].
+///
+/// This is what lets callers find slots for tables that have since been dropped from the
+/// publication, which would otherwise become orphans pinning WAL forever.
+pub async fn list_pipeline_replication_slots(
+    pool: &PgPool,
+    pipeline_id: u64,
+    slot_prefix: &str,
+) -> sqlx::Result<Vec<EtlReplicationSlot>> {
+    let apply_prefix = EtlReplicationSlot::apply_prefix(pipeline_id, slot_prefix).ok();
+    let table_sync_prefix = EtlReplicationSlot::table_sync_prefix(pipeline_id, slot_prefix).ok();
+
+    let slot_names: Vec<String> = sqlx::query_scalar("select slot_name from pg_replication_slots")
+        .fetch_all(pool)
+        .await?;
+
+    let slots = slot_names
+        .into_iter()
+        .filter(|slot_name| {
+            apply_prefix.as_deref() == Some(slot_name.as_str())
+                || table_sync_prefix
+                    .as_deref()
+                    .is_some_and(|prefix| slot_name.starts_with(prefix))
+        })
+        .filter_map(|slot_name| EtlReplicationSlot::try_from(slot_name.as_str()).ok())
+        .collect();
+
+    Ok(slots)
+}
+
+/// Drops table sync slots owned by the pipeline whose table is no longer in
+/// `active_table_ids`, without requiring the caller to enumerate every slot up front.
+///
+/// Only inactive orphaned slots are dropped; an active slot's table may simply be mid-sync and
+/// will be reconciled on its own once the sync finishes.
+pub async fn delete_orphaned_pipeline_replication_slots(
+    pool: &PgPool,
+    pipeline_id: u64,
+    active_table_ids: &[TableId],
+    slot_prefix: &str,
+) -> sqlx::Result<()> {
+    let slots = list_pipeline_replication_slots(pool, pipeline_id, slot_prefix).await?;
+
+    let mut orphaned_slot_names = Vec::new();
+    for slot in slots {
+        let EtlReplicationSlot::TableSync { table_id, .. } = &slot else {
+            continue;
+        };
+
+        if active_table_ids.contains(table_id) {
+            continue;
+        }
+
+        if let Ok(slot_name) = String::try_from(slot) {
+            orphaned_slot_names.push(slot_name);
+        }
+    }
+
+    if orphaned_slot_names.is_empty() {
+        return Ok(());
+    }
+
+    // Delete only inactive slots.
+    let query = String::from(
+        r#"
+        select pg_drop_replication_slot(r.slot_name)
+        from pg_replication_slots r
+        where r.slot_name = any($1) and r.active = false;
+        "#,
+    );
+    sqlx::query(&query)
+        .bind(orphaned_slot_names)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Persists how many messages have been applied at a slot's current `confirmed_flush_lsn`.
+///
+/// Postgres advances `confirmed_flush_lsn` itself once we report progress via replication
+/// feedback, but it has no notion of the in-LSN `offset` a single LSN's multiple row events
+/// need; call this after each committed batch with [`EtlReplicationSlot::resume_point_to_persist`]
+/// so a crash or restart resumes from exactly this position instead of re-snapshotting or
+/// risking dropped/duplicated rows.
+pub async fn persist_resume_offset(pool: &PgPool, slot_name: &str, offset: u64) -> sqlx::Result<()> {
+    sqlx::query(
+        r#"
+        insert into etl_replication_resume_offset (slot_name, resume_offset, updated_at)
+        values ($1, $2, now())
+        on conflict (slot_name) do update
+        set resume_offset = excluded.resume_offset, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(slot_name)
+    .bind(offset as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Resolves the full resume point for a worker's slot: the `confirmed_flush_lsn` Postgres has
+/// recorded for it, combined with the in-LSN offset we persisted via
+/// [`persist_resume_offset`]. Returns `None` when the slot has never confirmed a flush, in
+/// which case the caller should create the slot and start from its consistent point.
+pub async fn resolve_resume_point(
+    pool: &PgPool,
+    slot_name: &str,
+) -> sqlx::Result<Option<(PgLsn, u64)>> {
+    let Some(confirmed_flush_lsn) = EtlReplicationSlot::resume_point(pool, slot_name).await?
+    else {
+        return Ok(None);
+    };
+
+    let offset: Option<i64> = sqlx::query_scalar(
+        "select resume_offset from etl_replication_resume_offset where slot_name = $1",
+    )
+    .bind(slot_name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(Some((confirmed_flush_lsn, offset.unwrap_or(0) as u64)))
+}
+
+/// A slot's lag, paired with whether it exceeds the pipeline's configured WAL-retention
+/// threshold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotLagReport {
+    pub lag: SlotLag,
+    /// `true` when the slot is inactive and either its retained WAL exceeds
+    /// `max_slot_wal_bytes` or it has never confirmed a flush (unknown retention is treated as
+    /// exceeding). Active slots are never flagged.
+    pub exceeds_threshold: bool,
+}
+
+/// Reports WAL-retention lag for every replication slot owned by a pipeline, flagging any
+/// inactive slot whose retention exceeds `max_slot_wal_bytes` (typically
+/// `etl_config::shared::PipelineConfig::max_slot_wal_bytes` from the caller's config, though this
+/// takes the raw value to avoid a dependency on `etl-config`).
+///
+/// Long-lived inactive slots silently accumulate WAL and can take down the primary if nobody
+/// notices; this gives operators a single place to check before the disk fills.
+pub async fn report_slot_lag(
+    pool: &PgPool,
+    pipeline_id: u64,
+    slot_prefix: &str,
+    max_slot_wal_bytes: u64,
+) -> sqlx::Result<Vec<SlotLagReport>> {
+    let slots = list_pipeline_replication_slots(pool, pipeline_id, slot_prefix).await?;
+
+    let mut reports = Vec::with_capacity(slots.len());
+    for slot in slots {
+        let Ok(slot_name) = String::try_from(slot) else {
+            continue;
+        };
+
+        let Some(lag) = EtlReplicationSlot::lag(pool, &slot_name).await? else {
+            continue;
+        };
+
+        let exceeds_threshold = !lag.active
+            && match lag.retained_wal_bytes {
+                Some(bytes) => bytes.max(0) as u64 > max_slot_wal_bytes,
+                None => true,
+            };
+
+        reports.push(SlotLagReport {
+            lag,
+            exceeds_threshold,
+        });
+    }
+
+    Ok(reports)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -338,6 +684,9 @@ mod tests {
             EtlReplicationSlot::Apply {
                 pipeline_id: 13,
                 prefix: Cow::Owned("supabase_etl".to_string()),
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             }
         );
     }
@@ -350,6 +699,9 @@ mod tests {
             EtlReplicationSlot::Apply {
                 pipeline_id: 42,
                 prefix: Cow::Owned("myapp_prod".to_string()),
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             }
         );
     }
@@ -363,6 +715,9 @@ mod tests {
                 pipeline_id: 7,
                 table_id: TableId::new(12345_u32),
                 prefix: Cow::Owned("supabase_etl".to_string()),
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             }
         );
     }
@@ -376,6 +731,9 @@ mod tests {
                 pipeline_id: 7,
                 table_id: TableId::new(12345_u32),
                 prefix: Cow::Owned("custom".to_string()),
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             }
         );
     }
@@ -400,4 +758,82 @@ mod tests {
         let result: String = parsed.try_into().unwrap();
         assert_eq!(result, original);
     }
+
+    #[test]
+    fn test_with_resume_point_does_not_affect_slot_name() {
+        let slot = EtlReplicationSlot::for_apply_worker(1, DEFAULT_SLOT_PREFIX)
+            .with_resume_point(PgLsn::from(100), 3);
+
+        match &slot {
+            EtlReplicationSlot::Apply {
+                confirmed_flush_lsn,
+                resume_offset,
+                ..
+            } => {
+                assert_eq!(*confirmed_flush_lsn, Some(PgLsn::from(100)));
+                assert_eq!(*resume_offset, 3);
+            }
+            EtlReplicationSlot::TableSync { .. } => panic!("expected an apply slot"),
+        }
+
+        let result: String = slot.try_into().unwrap();
+        assert_eq!(result, "supabase_etl_apply_1");
+    }
+
+    #[test]
+    fn test_observe_message_skips_already_applied_messages_at_resume_lsn() {
+        let mut slot = EtlReplicationSlot::for_apply_worker(1, DEFAULT_SLOT_PREFIX)
+            .with_resume_point(PgLsn::from(100), 2);
+
+        // The first two messages at the resume LSN were already applied before the restart.
+        assert!(slot.observe_message(PgLsn::from(100)));
+        assert!(slot.observe_message(PgLsn::from(100)));
+        // From here on, messages at that LSN are new.
+        assert!(!slot.observe_message(PgLsn::from(100)));
+
+        // 2 skipped (already applied before restart) + 1 newly applied = 3 ever applied at this LSN.
+        assert_eq!(
+            slot.resume_point_to_persist(),
+            Some((PgLsn::from(100), 3))
+        );
+    }
+
+    #[test]
+    fn test_observe_message_does_not_skip_ordinary_messages_sharing_one_lsn() {
+        // A multi-row INSERT in one transaction decodes as several messages at the same LSN; on
+        // a slot with no resume point (no restart involved), none of them should be skipped.
+        let mut slot = EtlReplicationSlot::for_apply_worker(1, DEFAULT_SLOT_PREFIX);
+
+        for _ in 0..5 {
+            assert!(!slot.observe_message(PgLsn::from(300)));
+        }
+
+        assert_eq!(
+            slot.resume_point_to_persist(),
+            Some((PgLsn::from(300), 5))
+        );
+    }
+
+    #[test]
+    fn test_observe_message_clears_resume_point_once_lsn_advances() {
+        let mut slot = EtlReplicationSlot::for_apply_worker(1, DEFAULT_SLOT_PREFIX)
+            .with_resume_point(PgLsn::from(100), 5);
+
+        // A message at a later LSN means we've moved past anything that needs skipping.
+        assert!(!slot.observe_message(PgLsn::from(200)));
+        assert!(!slot.observe_message(PgLsn::from(200)));
+
+        assert_eq!(
+            slot.resume_point_to_persist(),
+            Some((PgLsn::from(200), 2))
+        );
+    }
+
+    #[test]
+    fn test_observe_message_with_no_resume_point_never_skips() {
+        let mut slot = EtlReplicationSlot::for_apply_worker(1, DEFAULT_SLOT_PREFIX);
+
+        assert!(!slot.observe_message(PgLsn::from(50)));
+        assert_eq!(slot.resume_point_to_persist(), Some((PgLsn::from(50), 1)));
+    }
 }
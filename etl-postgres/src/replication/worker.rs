@@ -1,8 +1,20 @@
 use std::borrow::Cow;
 
-use crate::replication::slots::EtlReplicationSlot;
+use sqlx::PgPool;
+use thiserror::Error;
+
+use crate::replication::slots::{self, EtlReplicationSlot, EtlReplicationSlotError};
 use crate::types::TableId;
 
+/// Error resolving a worker's replication start position.
+#[derive(Debug, Error)]
+pub enum ResolveStartError {
+    #[error(transparent)]
+    InvalidSlotName(#[from] EtlReplicationSlotError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
 /// Enum representing the types of workers that can be involved with a replication task.
 #[derive(Debug, Copy, Clone)]
 pub enum WorkerType {
@@ -21,12 +33,42 @@ impl WorkerType {
             Self::Apply => EtlReplicationSlot::Apply {
                 pipeline_id,
                 prefix,
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             },
             Self::TableSync { table_id } => EtlReplicationSlot::TableSync {
                 pipeline_id,
                 table_id: *table_id,
                 prefix,
+                confirmed_flush_lsn: None,
+                resume_offset: 0,
+                skip_remaining: 0,
             },
         }
     }
+
+    /// Builds this worker's slot and attaches any persisted resume point, so the caller can
+    /// start (or resume) replication from the right position without a separate lookup.
+    ///
+    /// If the slot does not exist yet or has never confirmed a flush, the returned slot has no
+    /// resume point attached and the caller should create the slot fresh, starting from its
+    /// consistent point. If the slot has a `confirmed_flush_lsn` but no offset was ever
+    /// persisted for it, the resume point is that LSN with an offset of 0.
+    pub async fn resolve_start(
+        &self,
+        pool: &PgPool,
+        pipeline_id: u64,
+        slot_prefix: impl Into<Cow<'static, str>>,
+    ) -> Result<EtlReplicationSlot, ResolveStartError> {
+        let slot = self.build_etl_replication_slot(pipeline_id, slot_prefix);
+        let slot_name: String = slot.clone().try_into()?;
+
+        let resume_point = slots::resolve_resume_point(pool, &slot_name).await?;
+
+        Ok(match resume_point {
+            Some((lsn, offset)) => slot.with_resume_point(lsn, offset),
+            None => slot,
+        })
+    }
 }